@@ -0,0 +1,69 @@
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Channel};
+use embassy_time::{Duration, Timer};
+use embedded_hal_async::digital::Wait;
+
+use crate::Button;
+
+/// How long a transition must hold steady before it's trusted as a real
+/// press/release rather than contact bounce.
+const DEBOUNCE_SETTLE: Duration = Duration::from_millis(25);
+
+/// Depth of the shared button event queue; comfortably covers a burst of
+/// presses on both buttons between `Ui::run` iterations.
+const EVENT_QUEUE_DEPTH: usize = 8;
+
+/// Which physical button a `ButtonEvent` originated from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Which {
+    A,
+    B,
+}
+
+impl Which {
+    /// A 0/1 index for the button, handy for indexing small per-button
+    /// arrays instead of matching on the variant everywhere.
+    pub fn index(self) -> usize {
+        match self {
+            Which::A => 0,
+            Which::B => 1,
+        }
+    }
+}
+
+/// A debounced press or release transition for one button.
+pub struct ButtonEvent {
+    pub which: Which,
+    pub pressed: bool,
+}
+
+/// Shared queue of debounced button transitions, drained by `Ui::run`.
+pub static BUTTON_EVENTS: Channel<ThreadModeRawMutex, ButtonEvent, EVENT_QUEUE_DEPTH> =
+    Channel::new();
+
+/// Watches a single button for edge transitions and pushes debounced
+/// `ButtonEvent`s onto `BUTTON_EVENTS`, decoupling input latency from
+/// whatever cadence `Ui::run` happens to be polling at.
+///
+/// On every edge, waits out `DEBOUNCE_SETTLE` and re-reads the pin; the
+/// transition is only published if the settled level still disagrees with
+/// the last reported state, otherwise it's dismissed as contact bounce.
+///
+/// # Arguments
+///
+/// * 'which' - which button this task instance is watching.
+/// * 'button' - the button to watch; owned for the life of the task.
+#[embassy_executor::task(pool_size = 2)]
+pub async fn watch_button(which: Which, mut button: Button) {
+    let mut pressed = false;
+
+    loop {
+        button.wait_for_any_edge().await;
+        Timer::after(DEBOUNCE_SETTLE).await;
+
+        let settled = button.is_low();
+        if settled != pressed {
+            pressed = settled;
+            BUTTON_EVENTS.send(ButtonEvent { which, pressed }).await;
+        }
+    }
+}
@@ -1,44 +1,116 @@
+use num_traits::float::FloatCore;
+
 use crate::*;
 
 /// Type alias for an SAADC with static lifetime and 1 channel.
 pub type Adc = saadc::Saadc<'static, 1>;
 
-/// A Knob that is a wrapper for an Adc
-pub struct Knob(Adc);
+/// Raw ADC readings are divided by this before being fed through the
+/// response curve, normalizing them to roughly 0.0..1.0.
+const RAW_SCALE: f32 = 10_000.0;
+
+/// A response curve mapping a normalized 0.0..1.0 knob reading to a level
+/// fraction before it's rescaled to 0..(LEVELS-1).
+///
+/// The curved variants approximate a logarithmic/exponential response with
+/// a quadratic rather than a true `log`/`powf`: neither is core-inherent
+/// under `#![no_std]`, nor provided by `FloatCore`, our no_std float trait.
+/// `Concave`/`Convex` name the actual shape produced so callers aren't
+/// promised a transcendental curve they're not getting.
+#[derive(Clone, Copy)]
+pub enum Curve {
+    /// Knob position maps directly to level.
+    Linear,
+    /// Expands the low end and compresses the high end, approximating a
+    /// logarithmic response; feels natural for a perceptual control like
+    /// brightness.
+    Concave,
+    /// The inverse of `Concave`: compresses the low end and expands the
+    /// high end, approximating an exponential response; suits a control
+    /// spanning a wide range, like frame rate.
+    Convex,
+}
+
+impl Curve {
+    /// Applies this curve to a normalized knob reading.
+    ///
+    /// # Arguments
+    ///
+    /// * 'fraction' - the normalized knob reading, clamped to 0.0..1.0.
+    ///
+    /// # Returns
+    ///
+    /// The curved fraction, also in 0.0..1.0.
+    fn apply(self, fraction: f32) -> f32 {
+        let fraction = fraction.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => fraction,
+            // `x * (2 - x)` is concave (expands the low end), and its
+            // inverse `x^2` is convex (expands the high end).
+            Curve::Concave => fraction * (2.0 - fraction),
+            Curve::Convex => fraction * fraction,
+        }
+    }
+}
+
+/// A Knob that wraps an Adc, mapping its readings to a 0..(LEVELS-1) level
+/// through a configurable response `Curve`, averaging `oversample`
+/// consecutive samples to suppress jitter near level boundaries.
+pub struct Knob {
+    adc: Adc,
+    curve: Curve,
+    oversample: usize,
+}
 
 impl Knob {
-    /// Creates a new knob with the provided adc.
+    /// Creates a new knob with the provided adc, response curve and
+    /// oversampling factor.
     ///
     /// # Arguments
     ///
     /// * 'adc' - An ADC to be used to read measurements.
+    /// * 'curve' - The response curve to map readings through.
+    /// * 'oversample' - How many consecutive samples to average per
+    ///   measurement; at least 1.
     ///
     /// # Returns
     ///
     /// A new Knob instance.
-    pub async fn new(adc: Adc) -> Self {
+    pub async fn new(adc: Adc, curve: Curve, oversample: usize) -> Self {
         adc.calibrate().await; // calibrate the provided adc
-        Self(adc) // return the new instance
+        Self {
+            adc,
+            curve,
+            oversample: oversample.max(1),
+        }
     }
 
-    /// Takes a measurement of the current adc status,
-    /// scales it and returns it as a u32.
+    /// Takes `oversample` measurements of the current adc status, averages
+    /// them, maps the result through the configured response curve, and
+    /// returns it rescaled to the 0..(LEVELS-1) range.
     ///
     /// # Returns
     ///
     /// A u32 value as a scaled value.
     pub async fn measure(&mut self) -> u32 {
-        // Take a sample from the single channel and store it in a buffer.
-        // The sample will be an i16 value.
-        let mut buf = [0];
-        self.0.sample(&mut buf).await;
+        // Average `oversample` consecutive samples to suppress jitter near
+        // level boundaries.
+        let mut total: u32 = 0;
+        for _ in 0..self.oversample {
+            // Take a sample from the single channel and store it in a
+            // buffer. The sample will be an i16 value.
+            let mut buf = [0];
+            self.adc.sample(&mut buf).await;
 
-        // Clamp the value to a max and convert it to a u16 from i16.
-        let raw = buf[0].clamp(0, 0x7fff) as u16;
+            // Clamp the value to a max and convert it to a u16 from i16.
+            let raw = buf[0].clamp(0, 0x7fff) as u16;
+            total += raw as u32;
+        }
+        let raw = total / self.oversample as u32;
 
         // Scale the raw value by converting to f32 and normalizing
-        // to between 0 and 1.
-        let scaled = raw as f32 / 10_000.0;
+        // to between 0 and 1, then map it through the response curve.
+        let scaled = self.curve.apply(raw as f32 / RAW_SCALE);
 
         // Rescale to the range specified by LEVELS.
         let result = ((LEVELS + 2) as f32 * scaled - 2.0)
@@ -1,11 +1,17 @@
 #![no_std]
 #![no_main]
 
+mod buttons;
 mod knob;
+mod levelmeter;
 mod rgb;
+mod settings;
 mod ui;
+pub use buttons::*;
 pub use knob::*;
+pub use levelmeter::*;
 pub use rgb::*;
+pub use settings::*;
 pub use ui::*;
 
 use panic_rtt_target as _;
@@ -19,9 +25,11 @@ use microbit_bsp::{
     embassy_nrf::{
         bind_interrupts,
         gpio::{AnyPin, Level, Output, OutputDrive},
+        nvmc::Nvmc,
+        peripherals::NVMC,
         saadc,
     },
-    Button, Microbit,
+    Button, LedMatrix, Microbit,
 };
 use num_traits::float::FloatCore;
 
@@ -29,6 +37,8 @@ use num_traits::float::FloatCore;
 pub static RGB_LEVELS: Mutex<ThreadModeRawMutex, [u32; 3]> = Mutex::new([0; 3]);
 /// Global value to store the current FRAME RATE value
 pub static FRAME_RATE: Mutex<ThreadModeRawMutex, u64> = Mutex::new(100);
+/// Global value to store the currently active rgb `EffectMode`.
+pub static EFFECT_MODE: Mutex<ThreadModeRawMutex, EffectMode> = Mutex::new(EffectMode::Manual);
 /// 16 levels for each RGB value.
 pub const LEVELS: u32 = 16;
 
@@ -84,9 +94,36 @@ where
     setter(&mut frame_rate);
 }
 
+/// Returns the currently active rgb effect mode from a global Mutex
+/// asynchronously.
+///
+/// Acquires a lock on the global EFFECT_MODE var for the duration of the fn.
+///
+/// # Returns
+///
+/// The current `EffectMode`.
+async fn get_effect_mode() -> EffectMode {
+    let effect_mode = EFFECT_MODE.lock().await;
+    *effect_mode
+}
+
+/// Sets the currently active rgb effect mode into a global Mutex
+/// asynchronously.
+///
+/// # Arguments
+/// * 'setter' - A setter function as a closure that can only be called once
+/// with a mutable reference to the `EffectMode`.
+async fn set_effect_mode<F>(setter: F)
+where
+    F: FnOnce(&mut EffectMode),
+{
+    let mut effect_mode = EFFECT_MODE.lock().await;
+    setter(&mut effect_mode);
+}
+
 /// Main function - is async and doesn't return.
 #[embassy_executor::main]
-async fn main(_spawner: Spawner) -> ! {
+async fn main(spawner: Spawner) -> ! {
     rtt_init_print!(); // Initialize rtt debug printing.
     let board = Microbit::default(); // Initialize the board with defaults.
 
@@ -95,6 +132,25 @@ async fn main(_spawner: Spawner) -> ! {
         SAADC => saadc::InterruptHandler;
     });
 
+    // Load the last-saved settings from flash, falling back to defaults on
+    // first boot (or after a layout change invalidates the stored record).
+    //
+    // `Microbit`'s board struct doesn't surface the NVMC peripheral as a
+    // named field the way it does the pins/buttons/display, so steal it
+    // directly instead; its registers are stateless, so there's no
+    // ownership hazard in taking it outside of `board`.
+    let mut nvmc = Nvmc::new(unsafe { NVMC::steal() });
+    let settings = Settings::load(&mut nvmc);
+
+    // Seed the globals the rgb and ui loops read from so the board comes
+    // back up showing its last state rather than off/default.
+    *RGB_LEVELS.lock().await = settings.levels;
+    *FRAME_RATE.lock().await = settings.frame_rate;
+
+    // Hand the flash controller off to its own task; Ui::run only ever
+    // requests saves/resets through SETTINGS_COMMANDS from here on.
+    spawner.spawn(persist_settings(nvmc)).unwrap();
+
     // Define a closure that helps to configure the individual rgb leds.
     // Takes a pin, and sets the level to low and drive to standard.
     let led_pin = |p| Output::new(p, Level::Low, OutputDrive::Standard);
@@ -104,8 +160,8 @@ async fn main(_spawner: Spawner) -> ! {
     let green = led_pin(AnyPin::from(board.p8));
     let blue = led_pin(AnyPin::from(board.p16));
 
-    // Group the led pins into an RGB struct with a specified frame rate.
-    let rgb: Rgb = Rgb::new([red, green, blue], 100);
+    // Group the led pins into an RGB struct with the saved frame rate.
+    let rgb: Rgb = Rgb::new([red, green, blue], settings.frame_rate);
 
     // Configure the SAADC with defaults, then set resolution to 14 bit.
     let mut saadc_config = saadc::Config::default();
@@ -119,11 +175,23 @@ async fn main(_spawner: Spawner) -> ! {
         [saadc::ChannelConfig::single_ended(board.p2)],
     );
 
-    // Initialize the knob interface with the initialized SAADC.
-    let knob = Knob::new(saadc).await;
+    // Initialize the knob interface with the initialized SAADC, using a
+    // concave curve (approximating logarithmic response) suited to the
+    // brightness controls; the frame-rate control shares the same knob
+    // and so gets the same curve. Oversampled 4x to smooth out jitter.
+    let knob = Knob::new(saadc, Curve::Concave, 4).await;
+
+    // Spawn a dedicated debouncing task per button; they publish stable
+    // press/release transitions onto BUTTON_EVENTS for the Ui loop to consume.
+    spawner.spawn(watch_button(Which::A, board.btn_a)).unwrap();
+    spawner.spawn(watch_button(Which::B, board.btn_b)).unwrap();
+
+    // Initialize the level meter display on the 5x5 led matrix.
+    let levelmeter = LevelMeter::new(board.display);
 
-    // Initialize the UI interface with the knob, and a,b board buttons.
-    let mut ui = Ui::new(knob, board.btn_a, board.btn_b);
+    // Initialize the UI interface with the knob, level meter and the
+    // settings it should start from.
+    let mut ui = Ui::new(knob, levelmeter, settings);
 
     // This is the main loop -
     // Run the rgb and ui loops concurrently by joining them.
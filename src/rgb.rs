@@ -1,14 +1,160 @@
 use crate::*;
+use num_traits::float::FloatCore;
 
 /// Type alias for array of AnyPins with static lifetime.
 type RgbPins = [Output<'static, AnyPin>; 3];
 
+/// Internal PWM resolution used for the gamma-corrected duty cycle.
+///
+/// This is much finer than the public `LEVELS` range so that the 16
+/// brightness steps the knob exposes can be spread evenly across the
+/// perceived brightness range instead of being bunched at the bright end.
+const DUTY_MAX: u32 = 255;
+
+/// Default perceptual gamma exponent applied to the 0..LEVELS knob range.
+/// Typical LEDs fall somewhere around 2.2-2.8; override per-board with
+/// `Rgb::with_gamma`.
+const DEFAULT_GAMMA: f32 = 2.6;
+
+/// A gamma-corrected duty-cycle lookup table, one entry per knob level.
+type DutyTable = [u32; LEVELS as usize];
+
+/// How many degrees the color-cycle hue advances per rendered frame; a
+/// full 360 degree cycle takes 120 frames, about 1.2 seconds at the
+/// default 100 fps.
+const HUE_STEP_DEGREES: f32 = 3.0;
+
+/// How far the breathing effect advances through its fade cycle per
+/// rendered frame.
+const BREATH_STEP: f32 = 1.0 / 90.0;
+
+/// The active output effect for the rgb subsystem.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EffectMode {
+    /// The static color set by the knob/Ui, as read from `RGB_LEVELS`.
+    Manual,
+    /// Cycles smoothly through the hue wheel at full saturation/value.
+    ColorCycle,
+    /// Fades the manually-set color up and down.
+    Breathing,
+}
+
+impl EffectMode {
+    /// Cycles to the next effect mode, wrapping back to 'Manual' after
+    /// 'Breathing'.
+    pub fn next(self) -> Self {
+        match self {
+            EffectMode::Manual => EffectMode::ColorCycle,
+            EffectMode::ColorCycle => EffectMode::Breathing,
+            EffectMode::Breathing => EffectMode::Manual,
+        }
+    }
+}
+
+/// Converts an HSV color to quantized per-channel levels in 0..(LEVELS-1),
+/// using the standard sextant algorithm.
+///
+/// # Arguments
+///
+/// * 'hue' - the hue in degrees; wrapped into 0..360.
+/// * 'saturation' - the saturation, clamped to 0.0..1.0.
+/// * 'value' - the value/brightness, clamped to 0.0..1.0.
+fn hsv_to_levels(hue: f32, saturation: f32, value: f32) -> [u32; 3] {
+    // `f32::rem_euclid` isn't core-inherent under `#![no_std]` (it's on
+    // `num_traits::Euclid`, which this crate doesn't pull in), so wrap the
+    // hue into 0..360 by hand instead.
+    let hue = hue % 360.0;
+    let hue = if hue < 0.0 { hue + 360.0 } else { hue };
+    let saturation = saturation.clamp(0.0, 1.0);
+    let value = value.clamp(0.0, 1.0);
+
+    let chroma = value * saturation;
+    let sextant_pos = (hue / 60.0) % 2.0 - 1.0;
+    let x = chroma * (1.0 - sextant_pos.abs());
+    let m = value - chroma;
+
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let quantize = |c: f32| (((c + m) * (LEVELS - 1) as f32).round() as u32).min(LEVELS - 1);
+    [quantize(r), quantize(g), quantize(b)]
+}
+
+/// Scales each channel level by `brightness`, for the breathing effect.
+fn scale_levels(levels: [u32; 3], brightness: f32) -> [u32; 3] {
+    levels.map(|level| ((level as f32 * brightness).round() as u32).min(LEVELS - 1))
+}
+
+/// A symmetric triangle wave over a 0.0..1.0 phase, used to fade the
+/// breathing effect up and down without needing trig functions.
+fn breathing_brightness(phase: f32) -> f32 {
+    if phase < 0.5 {
+        phase * 2.0
+    } else {
+        (1.0 - phase) * 2.0
+    }
+}
+
+/// Raises `fraction` (0.0..1.0) to the non-integer `gamma` power without a
+/// transcendental `powf`, which `FloatCore` (our `#![no_std]` float trait)
+/// doesn't provide. Linearly blends between the two neighboring integer
+/// powers, which `powi` does provide: `x^2.6` becomes a blend of `x^2` and
+/// `x^3` weighted by the 0.6 fractional part.
+///
+/// # Arguments
+///
+/// * 'fraction' - the base, in 0.0..1.0.
+/// * 'gamma' - the exponent to raise it to, typically 2.2-2.8.
+fn gamma_powf(fraction: f32, gamma: f32) -> f32 {
+    let lower = gamma.floor();
+    let weight = gamma - lower;
+    let lower_power = fraction.powi(lower as i32);
+    let upper_power = fraction.powi(lower as i32 + 1);
+    lower_power + weight * (upper_power - lower_power)
+}
+
+/// Builds a gamma-corrected duty-cycle lookup table.
+///
+/// `table[i] = round((i / (LEVELS - 1))^gamma * DUTY_MAX)`, so that each of
+/// the 16 brightness steps maps to a visually even increment instead of a
+/// linear (and therefore perceptually lopsided) on-time.
+///
+/// This is a plain `fn`, not a `const`, even though it only ever runs once
+/// per `Rgb` (at construction in `main`, not per frame): `gamma` is meant
+/// to be selectable per-board at runtime (see `Rgb::with_gamma`), and
+/// `powi`/`floor` aren't usable in a `const fn` on this toolchain, so a
+/// true compile-time LUT would only be possible by giving up runtime gamma
+/// selection entirely.
+///
+/// # Arguments
+///
+/// * 'gamma' - the gamma exponent to apply, typically 2.2-2.8.
+fn gamma_table(gamma: f32) -> DutyTable {
+    let mut table = [0u32; LEVELS as usize];
+    let mut i = 0;
+    while i < table.len() {
+        let fraction = i as f32 / (LEVELS - 1) as f32;
+        table[i] = (gamma_powf(fraction, gamma) * DUTY_MAX as f32).round() as u32;
+        i += 1;
+    }
+    table
+}
+
 /// An RGB led is represented here.
 pub struct Rgb {
     rgb: RgbPins, // The actual array of pins.
     // Shadow variables to minimize lock contention.
     levels: [u32; 3], // The levels for each pin as an array of u32.
     tick_time: u64,   // The duration of a tick as a u64.
+    duty_table: DutyTable, // Gamma-corrected on-time lookup, indexed by level.
+    hue: f32,         // Current hue for the ColorCycle effect, in degrees.
+    breath_phase: f32, // Current phase for the Breathing effect, in 0.0..1.0.
 }
 
 impl Rgb {
@@ -23,10 +169,11 @@ impl Rgb {
     /// The tick time in microseconds of a frame as a u64.
     fn frame_tick_time(frame_rate: u64) -> u64 {
         // divide from 1000000 to convert to microseconds.
-        1_000_000 / (3 * frame_rate * LEVELS as u64)
+        1_000_000 / (3 * frame_rate * DUTY_MAX as u64)
     }
 
-    /// Creates a new 'Rgb' instance with the given pins and frame rate.
+    /// Creates a new 'Rgb' instance with the given pins and frame rate,
+    /// using the board's default gamma-correction curve.
     ///
     /// # Arguments
     ///
@@ -37,36 +184,59 @@ impl Rgb {
     ///
     /// A new 'Rgb' instance.
     pub fn new(rgb: RgbPins, frame_rate: u64) -> Self {
+        Self::with_gamma(rgb, frame_rate, DEFAULT_GAMMA)
+    }
+
+    /// Creates a new 'Rgb' instance with an explicit, runtime-selectable
+    /// gamma exponent, for boards whose LEDs need a different perceptual
+    /// curve than `DEFAULT_GAMMA`.
+    ///
+    /// # Arguments
+    ///
+    /// * 'rgb' - The array of RgbPins.
+    /// * 'frame_rate' - the frame rate in fps for updating the led.
+    /// * 'gamma' - the gamma exponent to apply, typically 2.2-2.8.
+    ///
+    /// # Returns
+    ///
+    /// A new 'Rgb' instance.
+    pub fn with_gamma(rgb: RgbPins, frame_rate: u64, gamma: f32) -> Self {
         // calculate the tick_time from the frame_rate.
         let tick_time = Self::frame_tick_time(frame_rate);
         // return a new struct instance, setting initial levels to 0.
         Self {
             rgb,
-            levels: [0; 3], 
+            levels: [0; 3],
             tick_time,
+            duty_table: gamma_table(gamma),
+            hue: 0.0,
+            breath_phase: 0.0,
         }
     }
 
     /// Performs an on/off 'step' for a single, specified led.
     ///
     /// Turns on the LED for a duration in proportion to it's frame rate
-    /// and level then turns it off for the rest of the frame period.
+    /// and gamma-corrected level then turns it off for the rest of the
+    /// frame period.
     ///
     /// # Arguments
     ///
     /// * 'led' - a usize indicating which led to step.
     async fn step(&mut self, led: usize) {
-        // Get the current brightness for the specified led.
+        // Get the current brightness for the specified led and look up its
+        // gamma-corrected duty cycle.
         let level = self.levels[led];
+        let duty = self.duty_table[level as usize];
 
-        // Turn on led for a period of time if level is non-zero.
-        if level > 0 {
+        // Turn on led for a period of time if duty is non-zero.
+        if duty > 0 {
             // Turn on led.
-            self.rgb[led].set_high(); 
+            self.rgb[led].set_high();
 
-            // Calculate the time the led should be on for based on the current level,
-            // and the tick time.
-            let on_time = level as u64 * self.tick_time; 
+            // Calculate the time the led should be on for based on the
+            // gamma-corrected duty cycle and the tick time.
+            let on_time = duty as u64 * self.tick_time;
 
             // Wait for the specified time in microseconds.
             Timer::after_micros(on_time).await;
@@ -75,34 +245,50 @@ impl Rgb {
             self.rgb[led].set_low();
         }
 
-        // Calculate the new level for the off period.
-        let level = LEVELS - level;
+        // Calculate the complementary duty for the off period.
+        let duty = DUTY_MAX - duty;
 
-        // If level is still non-zero...
-        if level > 0 {
+        // If duty is still non-zero...
+        if duty > 0 {
             // Calculate the time for the off period.
-            let off_time = level as u64 * self.tick_time;
-            
+            let off_time = duty as u64 * self.tick_time;
+
             // Wait for specified time in microseconds.
             Timer::after_micros(off_time).await;
         }
     }
 
-    /// Continuously update the brightness of each led.
+    /// Continuously update the brightness of each led, rendering whichever
+    /// `EffectMode` is currently active.
     ///
     /// This function runs forever and so should never exit.
     pub async fn run(mut self) -> ! {
         loop {
-            // Get the current brightness levels for all leds
-            // and update internal value.
-            self.levels = get_rgb_levels().await;
-
             // Get the frame rate and calculate the tick time from it,
             // updating the internal value.
             let frame_rate = get_frame_rate().await;
             self.tick_time = Self::frame_tick_time(frame_rate);
 
-            // Update brightness of each led in sequence.
+            // Render the active effect's levels for this frame.
+            match get_effect_mode().await {
+                EffectMode::Manual => {
+                    self.levels = get_rgb_levels().await;
+                }
+                EffectMode::ColorCycle => {
+                    self.levels = hsv_to_levels(self.hue, 1.0, 1.0);
+                    self.hue = (self.hue + HUE_STEP_DEGREES) % 360.0;
+                }
+                EffectMode::Breathing => {
+                    let manual_levels = get_rgb_levels().await;
+                    let brightness = breathing_brightness(self.breath_phase);
+                    self.levels = scale_levels(manual_levels, brightness);
+                    self.breath_phase = (self.breath_phase + BREATH_STEP) % 1.0;
+                }
+            }
+
+            // Update brightness of each led in sequence. Stepping the
+            // hue/breath phase once per full three-led frame (above) keeps
+            // the animation's speed tied to the frame-rate knob.
             for led in 0..3 {
                 self.step(led).await;
             }
@@ -0,0 +1,128 @@
+use embassy_nrf::nvmc::Nvmc;
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Channel};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+/// Magic value identifying a valid settings record. Guards against loading
+/// garbage: an erased (or never-written) flash page reads back as all
+/// `0xff`, which will never happen to collide with this.
+const MAGIC: u32 = 0x52_47_42_63; // "RGBc"
+
+/// Bump whenever the on-flash layout of `Settings` changes, so a record
+/// written by an older layout is rejected instead of misparsed.
+const SETTINGS_VERSION: u8 = 1;
+
+/// Offset of the flash page reserved for persisted settings: the last
+/// page of the nRF52833's 512KiB flash, comfortably clear of the program
+/// image.
+const SETTINGS_OFFSET: u32 = 0x7F000;
+
+/// Size in bytes of a single flash page, and the minimum erase unit.
+const PAGE_SIZE: u32 = 4096;
+
+/// Size of the on-flash record: magic + version + 3 levels (1 byte each,
+/// they only ever span 0..LEVELS) + frame rate (8 bytes).
+const RECORD_LEN: usize = 4 + 1 + 3 + 8;
+
+/// The persisted rgb levels and frame rate, mirroring the fields `UiState`
+/// holds in RAM.
+#[derive(Clone, Copy)]
+pub struct Settings {
+    pub levels: [u32; 3],
+    pub frame_rate: u64,
+}
+
+impl Default for Settings {
+    /// The same 'white' defaults `UiState` resets to.
+    fn default() -> Self {
+        Self {
+            levels: [15, 4, 6],
+            frame_rate: 100,
+        }
+    }
+}
+
+impl Settings {
+    /// Serializes this record with a magic/version header so a future
+    /// layout change can tell old records apart from new ones.
+    fn to_bytes(self) -> [u8; RECORD_LEN] {
+        let mut buf = [0u8; RECORD_LEN];
+        buf[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        buf[4] = SETTINGS_VERSION;
+        buf[5] = self.levels[0] as u8;
+        buf[6] = self.levels[1] as u8;
+        buf[7] = self.levels[2] as u8;
+        buf[8..16].copy_from_slice(&self.frame_rate.to_le_bytes());
+        buf
+    }
+
+    /// Parses a record previously written by `to_bytes`, rejecting it if
+    /// the magic or version don't match (first boot, or a stale record
+    /// from a layout that has since changed).
+    fn from_bytes(buf: &[u8; RECORD_LEN]) -> Option<Self> {
+        let magic = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+        let version = buf[4];
+        if magic != MAGIC || version != SETTINGS_VERSION {
+            return None;
+        }
+
+        Some(Self {
+            levels: [buf[5] as u32, buf[6] as u32, buf[7] as u32],
+            frame_rate: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+        })
+    }
+
+    /// Loads the persisted settings from flash, falling back to
+    /// `Settings::default()` if no valid record is present.
+    ///
+    /// # Arguments
+    ///
+    /// * 'nvmc' - the flash controller to read from.
+    pub fn load(nvmc: &mut Nvmc) -> Self {
+        let mut buf = [0u8; RECORD_LEN];
+        match nvmc.read(SETTINGS_OFFSET, &mut buf) {
+            Ok(()) => Self::from_bytes(&buf).unwrap_or_default(),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Persists these settings to flash, erasing the reserved page first
+    /// since NOR flash can only be written after being erased.
+    ///
+    /// # Arguments
+    ///
+    /// * 'nvmc' - the flash controller to write to.
+    fn save(self, nvmc: &mut Nvmc) {
+        let _ = nvmc.erase(SETTINGS_OFFSET, SETTINGS_OFFSET + PAGE_SIZE);
+        let _ = nvmc.write(SETTINGS_OFFSET, &self.to_bytes());
+    }
+}
+
+/// A request for the settings-persistence task to act on. Kept off the Ui
+/// loop itself since flash erase/write is comparatively slow and gated
+/// behind an explicit gesture, not something to do inline.
+pub enum SettingsCommand {
+    /// Persist the given settings as the new saved state.
+    Save(Settings),
+    /// Overwrite the saved state with `Settings::default()`.
+    FactoryReset,
+}
+
+/// Queue of pending settings writes, sent to by `Ui::run` and drained by
+/// `persist_settings`.
+pub static SETTINGS_COMMANDS: Channel<ThreadModeRawMutex, SettingsCommand, 2> = Channel::new();
+
+/// Owns the flash controller and performs the writes `Ui::run` requests
+/// via `SETTINGS_COMMANDS`.
+///
+/// # Arguments
+///
+/// * 'nvmc' - the flash controller, moved in for the life of the task.
+#[embassy_executor::task]
+pub async fn persist_settings(mut nvmc: Nvmc<'static>) {
+    loop {
+        match SETTINGS_COMMANDS.receive().await {
+            SettingsCommand::Save(settings) => settings.save(&mut nvmc),
+            SettingsCommand::FactoryReset => Settings::default().save(&mut nvmc),
+        }
+    }
+}
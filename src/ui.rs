@@ -1,14 +1,25 @@
+use core::cell::Cell;
+use core::future::pending;
+
+use embassy_futures::select::{select3, Either3};
+use embassy_time::{Duration, Instant, Timer};
+
 use crate::*;
 
-/// Button State enum - Available button states.
-enum ButtonPressed {
-    Neither,
-    A,
-    B,
-    Both,
-}
+/// How long a button must stay held before it counts as a `LongPress`
+/// rather than a click.
+const LONG_PRESS: Duration = Duration::from_millis(700);
+
+/// How long to wait after a release for a second press before settling on
+/// a `SingleClick` instead of a `DoubleClick`.
+const DOUBLE_CLICK_WINDOW: Duration = Duration::from_millis(300);
+
+/// How long both buttons must be held together before the current state
+/// is saved to flash.
+const BOTH_HELD_SAVE: Duration = Duration::from_secs(2);
 
 /// Control Enum - Available controls to modify.
+#[derive(Clone, Copy)]
 enum Control {
     RedLed,
     GreenLed,
@@ -16,6 +27,121 @@ enum Control {
     FrameRate,
 }
 
+impl Control {
+    /// Cycles to the next control in the sequence, wrapping back to
+    /// 'RedLed' after 'FrameRate'.
+    ///
+    /// # Returns
+    ///
+    /// The next 'Control' in the cycle.
+    fn next(self) -> Self {
+        match self {
+            Control::RedLed => Control::GreenLed,
+            Control::GreenLed => Control::BlueLed,
+            Control::BlueLed => Control::FrameRate,
+            Control::FrameRate => Control::RedLed,
+        }
+    }
+}
+
+/// A discrete button gesture recognized by `GestureTracker`, as opposed to
+/// the raw press/release transitions a `ButtonEvent` reports.
+enum Gesture {
+    SingleClick,
+    DoubleClick,
+    LongPress,
+}
+
+/// Where a `GestureTracker` is in recognizing a gesture.
+enum TrackerState {
+    Idle,
+    Pressed { deadline: Instant },
+    WaitingSecondPress { deadline: Instant },
+}
+
+/// Turns a stream of debounced press/release transitions for a single
+/// button into `Gesture`s.
+///
+/// Tracks just enough state to tell a long-held button from a quick
+/// click, and a single click from the first half of a double-click: a
+/// press starts a `LONG_PRESS` deadline, and a release before that
+/// deadline starts a fresh `DOUBLE_CLICK_WINDOW` deadline for a second
+/// press to arrive within.
+struct GestureTracker {
+    state: TrackerState,
+}
+
+impl GestureTracker {
+    fn new() -> Self {
+        Self {
+            state: TrackerState::Idle,
+        }
+    }
+
+    /// The next instant `on_timeout` should be called, if this tracker has
+    /// a gesture pending resolution.
+    fn deadline(&self) -> Option<Instant> {
+        match self.state {
+            TrackerState::Idle => None,
+            TrackerState::Pressed { deadline } => Some(deadline),
+            TrackerState::WaitingSecondPress { deadline } => Some(deadline),
+        }
+    }
+
+    /// Feeds a debounced press (`pressed == true`) or release transition
+    /// into the state machine.
+    ///
+    /// # Returns
+    ///
+    /// A completed `Gesture`, if this transition resolved one.
+    fn on_transition(&mut self, pressed: bool, now: Instant) -> Option<Gesture> {
+        match (&self.state, pressed) {
+            (TrackerState::Idle, true) => {
+                self.state = TrackerState::Pressed {
+                    deadline: now + LONG_PRESS,
+                };
+                None
+            }
+            (TrackerState::Pressed { .. }, false) => {
+                self.state = TrackerState::WaitingSecondPress {
+                    deadline: now + DOUBLE_CLICK_WINDOW,
+                };
+                None
+            }
+            (TrackerState::WaitingSecondPress { .. }, true) => {
+                self.state = TrackerState::Idle;
+                Some(Gesture::DoubleClick)
+            }
+            // A release arriving while idle: the release that ends a
+            // long-press or a double-click already reported, or a stray
+            // release with nothing pressed; nothing to do with any of them.
+            _ => None,
+        }
+    }
+
+    /// Called once `deadline()` has elapsed; finalizes whatever gesture
+    /// was waiting on it and resets to idle.
+    fn on_timeout(&mut self) -> Option<Gesture> {
+        match self.state {
+            TrackerState::Pressed { .. } => {
+                self.state = TrackerState::Idle;
+                Some(Gesture::LongPress)
+            }
+            TrackerState::WaitingSecondPress { .. } => {
+                self.state = TrackerState::Idle;
+                Some(Gesture::SingleClick)
+            }
+            TrackerState::Idle => None,
+        }
+    }
+
+    /// Discards any gesture in progress, e.g. because the other button
+    /// just joined in to form a two-button combo instead.
+    fn cancel(&mut self) {
+        self.state = TrackerState::Idle;
+    }
+}
+
 /// Represents the UI state.
 struct UiState {
     levels: [u32; 3], // levels for each of the 3 led colors.
@@ -58,56 +184,50 @@ impl Default for UiState {
     }
 }
 
-/// Represents the UI of the program with a knob, A and B buttons and state.
+/// Represents the UI of the program with a knob and state, driven by
+/// debounced button events published on `BUTTON_EVENTS`.
 pub struct Ui {
     knob: Knob,             // a knob to control the frame rate or brightness.
-    button_a: Button,       // Button A on the microbit.
-    button_b: Button,       // Button B on the microbit.
     levelmeter: LevelMeter, // Level Meter
     state: UiState,         // The state of the UI.
+    // Control the knob currently edits; cycled by a single-click on
+    // either button.
+    active_control: Cell<Control>,
+    // Whether the level meter display is currently shown; toggled by a
+    // double-click on button B.
+    meter_enabled: Cell<bool>,
 }
 
 impl Ui {
-    /// Create a new Ui instance with the given arguments. Configure the
-    /// UiState with default values. The Knob controls the frame rate,
-    /// and holding the buttons changes the control to modify a color
-    /// brightness level.
+    /// Create a new Ui instance with the given arguments, starting from
+    /// `initial` (typically whatever `Settings::load` last persisted). The
+    /// knob edits whichever `Control` is active; single-clicking either
+    /// button cycles the active control, double-clicking B toggles the
+    /// level meter, double-clicking A cycles the active rgb `EffectMode`,
+    /// long-pressing A factory-resets back to defaults, and holding both
+    /// buttons for `BOTH_HELD_SAVE` saves the current state to flash.
+    /// Button input itself is read from `BUTTON_EVENTS`, populated by the
+    /// per-button debouncing tasks spawned in `main`.
     ///
     /// # Arguments
     ///
     /// * 'knob' - The control for modifying brightness settings.
-    /// * 'button_a' - The A button on the Microbit.
-    /// * 'button_b' - The B button on the Microbit.
+    /// * 'levelmeter' - The level meter display.
+    /// * 'initial' - The rgb levels and frame rate to start from.
     ///
     /// # Returns
     ///
     /// A new 'Ui' instance.
-    pub fn new(knob: Knob, button_a: Button, button_b: Button, levelmeter: LevelMeter) -> Self {
+    pub fn new(knob: Knob, levelmeter: LevelMeter, initial: Settings) -> Self {
         Self {
             knob,
-            button_a,
-            button_b,
             levelmeter,
-            state: UiState::default(),
-        }
-    }
-
-    /// Figures out which combination of buttons is being pressed and then
-    /// returns the appropriate enum value.
-    ///
-    /// # Returns
-    ///
-    /// A 'ButtonPressed' enum value correspoding to which buttons are pressed.
-    fn button_state(&self) -> ButtonPressed {
-        let a_pressed = self.button_a.is_low(); // check if button a is pressed.
-        let b_pressed = self.button_b.is_low(); // check if button b is pressed.
-
-        // Match the state of buttons pressed and return the appropriate value.
-        match (a_pressed, b_pressed) {
-            (true, true) => ButtonPressed::Both,
-            (true, false) => ButtonPressed::A,
-            (false, true) => ButtonPressed::B,
-            (false, false) => ButtonPressed::Neither,
+            state: UiState {
+                levels: initial.levels,
+                frame_rate: initial.frame_rate,
+            },
+            active_control: Cell::new(Control::FrameRate),
+            meter_enabled: Cell::new(true),
         }
     }
 
@@ -121,90 +241,243 @@ impl Ui {
     /// # Returns
     ///
     /// A scaled level to be used as a frame rate as a u64.
-    fn frame_rate_from_level(&self, level: u32) -> u64 {
+    fn frame_rate_from_level(level: u32) -> u64 {
         let scaled_level = (level + 1) * 10;
         scaled_level as u64
     }
 
+    /// Measures the knob, applies it to the currently active control, and
+    /// pushes any change out to the rgb and display subsystems.
+    ///
+    /// # Arguments
+    ///
+    /// * 'knob' - the knob to measure.
+    /// * 'state' - the UiState to update.
+    /// * 'levelmeter' - the level meter display to refresh.
+    /// * 'active_control' - which control the knob currently edits.
+    /// * 'meter_enabled' - whether the level meter display is shown.
+    async fn tick(
+        knob: &mut Knob,
+        state: &mut UiState,
+        levelmeter: &mut LevelMeter,
+        active_control: &Cell<Control>,
+        meter_enabled: &Cell<bool>,
+    ) {
+        // Measure the knob's current position.
+        let level = knob.measure().await;
+
+        // Flag to indicate if a level has been changed.
+        let mut control_changed = false;
+
+        // Adjust the led color or frame rate corresponding to the active
+        // control.
+        match active_control.get() {
+            Control::RedLed => {
+                if level != state.levels[0] {
+                    state.levels[0] = level;
+                    control_changed = true;
+                }
+            }
+            Control::GreenLed => {
+                if level != state.levels[1] {
+                    state.levels[1] = level;
+                    control_changed = true;
+                }
+            }
+            Control::BlueLed => {
+                if level != state.levels[2] {
+                    state.levels[2] = level;
+                    control_changed = true;
+                }
+            }
+            Control::FrameRate => {
+                let frame_rate = Self::frame_rate_from_level(level);
+                if frame_rate != state.frame_rate {
+                    state.frame_rate = frame_rate;
+                    control_changed = true;
+                }
+            }
+        }
+
+        // Display and update the new values only if a change has occurred.
+        if control_changed {
+            Self::publish_state(state).await;
+        }
+
+        // Update the levelmeter every step, unless it's been toggled off.
+        // This adds a 50ms delay to avoid overmeasuring the knob level. The
+        // meter-disabled path below paces itself the same amount, since it
+        // would otherwise have nothing to await and would spin on the knob.
+        if meter_enabled.get() {
+            levelmeter
+                .update_display(state.levels, state.frame_rate)
+                .await;
+        } else {
+            Timer::after_millis(50).await;
+        }
+    }
+
+    /// Prints the current state and pushes it out to the global rgb levels
+    /// and frame rate Mutexes.
+    async fn publish_state(state: &UiState) {
+        // Print the current state.
+        state.show();
+
+        // Update the global rgb levels Mutex.
+        set_rgb_levels(|rgb| {
+            *rgb = state.levels;
+        })
+        .await;
+
+        // Update the global frame_rate level Mutex
+        set_frame_rate(|frame_rate| {
+            *frame_rate = state.frame_rate;
+        })
+        .await;
+    }
+
+    /// Applies a gesture recognized on button A: a single click cycles the
+    /// active control, a double click cycles the active rgb `EffectMode`,
+    /// and a long press factory-resets the UiState (and the persisted
+    /// flash record) back to defaults.
+    async fn handle_button_a_gesture(
+        gesture: Gesture,
+        state: &mut UiState,
+        active_control: &Cell<Control>,
+    ) {
+        match gesture {
+            Gesture::SingleClick => active_control.set(active_control.get().next()),
+            Gesture::DoubleClick => {
+                set_effect_mode(|mode| *mode = mode.next()).await;
+            }
+            Gesture::LongPress => {
+                *state = UiState::default();
+                Self::publish_state(state).await;
+                SETTINGS_COMMANDS.send(SettingsCommand::FactoryReset).await;
+            }
+        }
+    }
+
+    /// Applies a gesture recognized on button B: a single click cycles the
+    /// active control, and a double click toggles the level meter.
+    fn handle_button_b_gesture(
+        gesture: Gesture,
+        active_control: &Cell<Control>,
+        meter_enabled: &Cell<bool>,
+    ) {
+        match gesture {
+            Gesture::SingleClick => active_control.set(active_control.get().next()),
+            Gesture::DoubleClick => meter_enabled.set(!meter_enabled.get()),
+            Gesture::LongPress => {}
+        }
+    }
+
     /// The main Ui loop, which measures and reports the current values.
     ///
-    /// When program starts, it reads the current knob position and updates the
-    /// levels accordingly and prints that info to the console.
-    /// Then it goes into the main loop which measures,
-    /// updates and prints the info forever.
+    /// Races the knob/display tick against `BUTTON_EVENTS` and whichever
+    /// gesture deadline is soonest, so that single-clicks, double-clicks
+    /// and long-presses are all recognized independent of the knob's 50ms
+    /// measurement cadence.
     pub async fn run(&mut self) -> ! {
         // Display the Ui state info.
         self.state.show();
 
-        // Main loop which continuously measures the knob position and
-        // updates the state levels accordingly.
+        // Split into disjoint field borrows so the knob tick can run
+        // concurrently with button event handling.
+        let Ui {
+            knob,
+            levelmeter,
+            state,
+            active_control,
+            meter_enabled,
+        } = self;
+
+        let mut gesture_a = GestureTracker::new();
+        let mut gesture_b = GestureTracker::new();
+        // Whether each button is currently held, tracked from BUTTON_EVENTS
+        // so the both-held-to-save combo can be recognized independent of
+        // the per-button gesture trackers above.
+        let mut pressed = [false, false];
+        let mut both_held_deadline: Option<Instant> = None;
+
         loop {
-            // Measure the knob's current position
-            let level = self.knob.measure().await;
-
-            // Flag to indicate if a level has been changed.
-            let mut control_changed = false;
-
-            // Choose the appropriate control to modify based on which buttons
-            // are being pressed.
-            let control = match self.button_state() {
-                ButtonPressed::Both => Control::RedLed,
-                ButtonPressed::A => Control::BlueLed,
-                ButtonPressed::B => Control::GreenLed,
-                ButtonPressed::Neither => Control::FrameRate,
+            let deadline = [gesture_a.deadline(), gesture_b.deadline(), both_held_deadline]
+                .into_iter()
+                .flatten()
+                .min();
+
+            let next_deadline = async {
+                match deadline {
+                    Some(at) => Timer::at(at).await,
+                    None => pending().await,
+                }
             };
 
-            // Adjust the led color corresponding to the control selected.
-            match control {
-                Control::RedLed => {
-                    if level != self.state.levels[0] {
-                        self.state.levels[0] = level;
-                        control_changed = true;
+            match select3(
+                Self::tick(knob, state, levelmeter, active_control, meter_enabled),
+                BUTTON_EVENTS.receive(),
+                next_deadline,
+            )
+            .await
+            {
+                Either3::First(()) => {}
+                Either3::Second(event) => {
+                    let now = Instant::now();
+                    pressed[event.which.index()] = event.pressed;
+                    let both_down = pressed[0] && pressed[1];
+
+                    if both_down {
+                        // The combo owns input from here; discard whatever
+                        // either single-button tracker had in flight so a
+                        // stray click/long-press doesn't also fire.
+                        both_held_deadline.get_or_insert(now + BOTH_HELD_SAVE);
+                        gesture_a.cancel();
+                        gesture_b.cancel();
+                    } else {
+                        both_held_deadline = None;
+                        let tracker = match event.which {
+                            Which::A => &mut gesture_a,
+                            Which::B => &mut gesture_b,
+                        };
+                        if let Some(gesture) = tracker.on_transition(event.pressed, now) {
+                            match event.which {
+                                Which::A => {
+                                    Self::handle_button_a_gesture(gesture, state, active_control)
+                                        .await
+                                }
+                                Which::B => Self::handle_button_b_gesture(
+                                    gesture,
+                                    active_control,
+                                    meter_enabled,
+                                ),
+                            }
+                        }
                     }
                 }
-                Control::GreenLed => {
-                    if level != self.state.levels[1] {
-                        self.state.levels[1] = level;
-                        control_changed = true;
+                Either3::Third(()) => {
+                    let now = Instant::now();
+                    if gesture_a.deadline().is_some_and(|d| d <= now) {
+                        if let Some(gesture) = gesture_a.on_timeout() {
+                            Self::handle_button_a_gesture(gesture, state, active_control).await;
+                        }
                     }
-                }
-                Control::BlueLed => {
-                    if level != self.state.levels[2] {
-                        self.state.levels[2] = level;
-                        control_changed = true;
+                    if gesture_b.deadline().is_some_and(|d| d <= now) {
+                        if let Some(gesture) = gesture_b.on_timeout() {
+                            Self::handle_button_b_gesture(gesture, active_control, meter_enabled);
+                        }
                     }
-                }
-                Control::FrameRate => {
-                    let frame_rate = self.frame_rate_from_level(level);
-                    if frame_rate != self.state.frame_rate {
-                        self.state.frame_rate = frame_rate;
-                        control_changed = true;
+                    if both_held_deadline.is_some_and(|d| d <= now) && pressed[0] && pressed[1] {
+                        both_held_deadline = None;
+                        let settings = Settings {
+                            levels: state.levels,
+                            frame_rate: state.frame_rate,
+                        };
+                        SETTINGS_COMMANDS
+                            .send(SettingsCommand::Save(settings))
+                            .await;
                     }
                 }
             }
-
-            // Display and update the new values only if a change has occurred.
-            if control_changed {
-                // Print the current state.
-                self.state.show();
-
-                // Update the global rgb levels Mutex.
-                set_rgb_levels(|rgb| {
-                    *rgb = self.state.levels;
-                })
-                .await;
-
-                // Update the global frame_rate level Mutex
-                set_frame_rate(|frame_rate| {
-                    *frame_rate = self.state.frame_rate;
-                })
-                .await;
-            }
-
-            // Update the levelmeter every step.
-            // This adds a 50ms delay to avoid overmeasuring
-            // the knob level.
-            self.levelmeter.update_display(self.state.levels).await;
         }
     }
 }